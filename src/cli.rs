@@ -0,0 +1,96 @@
+//! Command-line parsing and serial-port setup.
+
+use std::error::Error;
+
+use clap::Parser;
+use serialport::UsbPortInfo;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "DSMR smart meter logger")]
+pub struct Cli {
+    /// Serial port to read telegrams from (e.g. /dev/ttyUSB0). Auto-detected when omitted.
+    #[arg(long)]
+    pub port: Option<String>,
+
+    /// Baud rate of the serial connection.
+    #[arg(long, default_value_t = 115_200)]
+    pub baud: u32,
+
+    /// Path to the CSV log file.
+    #[arg(long, default_value = "log.csv")]
+    pub log_file: String,
+
+    /// Path to the diagnostic log file (serial/parse errors never go to stdout).
+    #[arg(long, default_value = "dsm_logger.log")]
+    pub diag_log: String,
+
+    /// Run against simulated data instead of a real meter.
+    #[arg(long)]
+    pub demo: bool,
+
+    /// Show a scrolling egui_plot time-series view instead of the terminal bar charts.
+    #[arg(long)]
+    pub plot: bool,
+}
+
+/// Builds the `serial` port settings for `baud`, 8N1, no flow control.
+pub fn port_settings(baud: serial::BaudRate) -> serial::PortSettings {
+    serial::PortSettings {
+        baud_rate: baud,
+        char_size: serial::Bits8,
+        parity: serial::ParityNone,
+        stop_bits: serial::Stop1,
+        flow_control: serial::FlowNone,
+    }
+}
+
+/// Maps a plain baud number onto the `serial` crate's `BaudRate` (some non-DSMR5 meters run at
+/// e.g. 9600), falling back to `BaudOther` for custom rates the enum doesn't name.
+pub fn baud_rate(baud: u32) -> serial::BaudRate {
+    match baud {
+        110 => serial::Baud110,
+        300 => serial::Baud300,
+        600 => serial::Baud600,
+        1200 => serial::Baud1200,
+        2400 => serial::Baud2400,
+        4800 => serial::Baud4800,
+        9600 => serial::Baud9600,
+        19200 => serial::Baud19200,
+        38400 => serial::Baud38400,
+        57600 => serial::Baud57600,
+        115_200 => serial::Baud115200,
+        other => serial::BaudOther(other as usize),
+    }
+}
+
+/// Picks a serial port when `--port` wasn't given: auto-selects if exactly one attached USB
+/// device looks like a smart-meter (P1) cable, otherwise lists what's available and errors out.
+pub fn discover_port() -> Result<String, Box<dyn Error>> {
+    let ports = serialport::available_ports()?;
+
+    let candidates: Vec<_> = ports
+        .iter()
+        .filter(|p| matches!(&p.port_type, serialport::SerialPortType::UsbPort(info) if is_smart_meter_cable(info)))
+        .collect();
+
+    if let [port] = candidates.as_slice() {
+        return Ok(port.port_name.clone());
+    }
+
+    for port in &ports {
+        println!("{}", port.port_name);
+    }
+
+    Err(format!(
+        "no --port given and no single smart-meter cable detected ({} port(s) available)",
+        ports.len()
+    )
+    .into())
+}
+
+/// P1 USB cables are almost always built around an FTDI FT230X UART bridge. Require the FT230X
+/// product match itself, rather than just "some FTDI device", so other FTDI-based adapters
+/// (Arduinos, generic USB-TTL dongles) don't get silently auto-selected.
+fn is_smart_meter_cable(info: &UsbPortInfo) -> bool {
+    info.product.as_deref().is_some_and(|p| p.contains("FT230X"))
+}
@@ -0,0 +1,71 @@
+//! extcap integration: lets `dsm_logger` act as a live capture source inside
+//! Wireshark, so smart-meter traffic can be inspected alongside other
+//! captures. Wireshark drives an extcap binary through a small handshake
+//! (`--extcap-interfaces`, `--extcap-config`, `--extcap-dlts`) before finally
+//! invoking `--capture --fifo <path> --extcap-interface <name>` to stream
+//! packets. See the extcap spec:
+//! https://www.wireshark.org/docs/wsdg_html_chunked/ChCaptureExtcap.html
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+
+use chrono::{DateTime, Local};
+
+/// Name Wireshark shows in its interface list and passes back via `--extcap-interface`.
+pub const INTERFACE: &str = "dsmr5";
+
+/// LINKTYPE_USER10 (147 + 10); DSMR5 has no registered LINKTYPE of its own, so it
+/// borrows one of the "User" slots tcpdump/Wireshark reserve for exactly this purpose.
+const DLT_USER10: u32 = 157;
+
+pub fn print_interfaces() {
+    println!("extcap {{version=1.0}}{{help=https://github.com/vDorst/dsm_logger}}");
+    println!("interface {{value={INTERFACE}}}{{display=DSMR smart meter}}");
+}
+
+pub fn print_dlts() {
+    println!("dlt {{number={DLT_USER10}}}{{name=USER10}}{{display=DSMR5 telegram}}");
+}
+
+pub fn print_config() {
+    println!("arg {{number=0}}{{call=--port}}{{display=Serial port}}{{type=string}}{{required=true}}");
+    println!("arg {{number=1}}{{call=--baud}}{{display=Baud rate}}{{type=unsigned}}{{default=115200}}");
+}
+
+/// Writes pcap records for each telegram into the FIFO Wireshark reads from.
+pub struct PcapWriter {
+    fifo: std::fs::File,
+}
+
+impl PcapWriter {
+    /// Opens `fifo_path` and writes the pcap global header.
+    ///
+    /// The `open()` call blocks until Wireshark opens its end of the FIFO.
+    pub fn open(fifo_path: &str) -> io::Result<PcapWriter> {
+        let mut fifo = OpenOptions::new().write(true).open(fifo_path)?;
+
+        // pcap global header, see https://wiki.wireshark.org/Development/LibpcapFileFormat
+        fifo.write_all(&0xa1b2_c3d4_u32.to_ne_bytes())?; // magic number
+        fifo.write_all(&2u16.to_ne_bytes())?; // version major
+        fifo.write_all(&4u16.to_ne_bytes())?; // version minor
+        fifo.write_all(&0i32.to_ne_bytes())?; // thiszone
+        fifo.write_all(&0u32.to_ne_bytes())?; // sigfigs
+        fifo.write_all(&65535u32.to_ne_bytes())?; // snaplen
+        fifo.write_all(&DLT_USER10.to_ne_bytes())?; // network (linktype)
+        fifo.flush()?;
+
+        Ok(PcapWriter { fifo })
+    }
+
+    /// Writes one packet record and flushes so Wireshark's live view keeps up.
+    pub fn write_packet(&mut self, time: DateTime<Local>, payload: &[u8]) -> io::Result<()> {
+        let len = payload.len() as u32;
+
+        self.fifo.write_all(&(time.timestamp() as u32).to_ne_bytes())?;
+        self.fifo.write_all(&time.timestamp_subsec_micros().to_ne_bytes())?;
+        self.fifo.write_all(&len.to_ne_bytes())?; // captured length
+        self.fifo.write_all(&len.to_ne_bytes())?; // original length
+        self.fifo.write_all(payload)?;
+        self.fifo.flush()
+    }
+}
@@ -0,0 +1,20 @@
+//! Diagnostic logging to a file, since stdout is reserved for the TUI.
+//!
+//! The TUI runs in crossterm's raw/alternate-screen mode, where a stray
+//! `println!` scrambles the rendered bar charts. Route every serial/parse/
+//! channel error through the `log` crate instead of stdout; `simplelog`'s
+//! `WriteLogger` serializes writes to the configured file behind its own
+//! mutex, so this is safe to call from the serial and demo threads too.
+
+use std::fs::OpenOptions;
+use std::io;
+
+use simplelog::{Config, LevelFilter, WriteLogger};
+
+/// Opens (or creates) `path` and installs it as the global `log` destination.
+pub fn init(path: &str) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    WriteLogger::init(LevelFilter::Warn, Config::default(), file)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
@@ -16,22 +16,106 @@ use tui::{
     Frame, Terminal,
 };
 use rand::{self, Rng, thread_rng};
-use std::sync::mpsc::{channel, Receiver, Sender};
+use crossbeam_channel::{select, unbounded, Receiver, Sender};
 use chrono::{Local, prelude::*};
 use serial::{self, unix::TTYPort, SerialPort};
 use std::io::{BufWriter, Read};
 use std::fs::{File, OpenOptions};
 
-struct MeterData {
-    time: DateTime<Local>,
-    watt: u64,
-    total: [u64; 2],
+mod cli;
+mod extcap;
+mod logging;
+mod plot;
+
+use clap::Parser;
+use cli::{baud_rate, discover_port, port_settings, Cli};
+use log::{error, warn};
+
+pub(crate) struct MeterData {
+    pub(crate) time: DateTime<Local>,
+    pub(crate) watt: u64,
+    pub(crate) power_returned: u64,
+    pub(crate) total: [u64; 2],
+    pub(crate) tariff_indicator: u8,
+    pub(crate) gas: Option<f64>,
+    pub(crate) voltage: [Option<f64>; 3],
+    pub(crate) current: [Option<u64>; 3],
+}
+
+/// Commands the UI thread sends to the serial thread to steer the connection live.
+enum SerialPortCmd {
+    /// Drop the current connection and wait for a further command instead of retrying `path`.
+    Disconnect,
+    Connect(String),
+}
+
+/// Formats an optional CSV field, rendering a missing value as an empty cell.
+fn fmt_opt<T: std::fmt::Display>(v: Option<T>) -> String {
+    v.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// Appends CSV rows and tracks the `AVG_SAMPLES` moving average, shared by every view so the TUI
+/// bar charts and the egui_plot view log identical data.
+pub(crate) struct MeterLog {
+    log: BufWriter<File>,
+    recent_watts: Vec<u64>,
+}
+
+impl MeterLog {
+    pub(crate) fn new(log: BufWriter<File>) -> MeterLog {
+        MeterLog {
+            log,
+            recent_watts: Vec::with_capacity(AVG_SAMPLES),
+        }
+    }
+
+    /// Appends one CSV row for `data` and returns the `AVG_SAMPLES` moving average of its watt value.
+    pub(crate) fn record(&mut self, data: &MeterData) -> u64 {
+        if self.recent_watts.len() == AVG_SAMPLES {
+            self.recent_watts.pop();
+        }
+
+        let avg_len = std::cmp::min(self.recent_watts.len(), AVG_SAMPLES);
+        let avg_xsec = (data.watt + self.recent_watts[0..avg_len].iter().sum::<u64>()) / (avg_len + 1) as u64;
+
+        let mut logstr = String::with_capacity(255);
+        logstr.push_str(data.time.format("%Y-%m-%d %H:%M:%S").to_string().as_str());
+        logstr.push_str(
+            format!(
+                ";{};{};{};{};{};{};{};{};{};{};{};{};{};\n",
+                data.total[0],
+                data.total[1],
+                data.watt,
+                avg_xsec,
+                data.power_returned,
+                data.tariff_indicator,
+                fmt_opt(data.gas),
+                fmt_opt(data.voltage[0]),
+                fmt_opt(data.voltage[1]),
+                fmt_opt(data.voltage[2]),
+                fmt_opt(data.current[0]),
+                fmt_opt(data.current[1]),
+                fmt_opt(data.current[2]),
+            )
+            .as_str(),
+        );
+        if let Err(e) = self.log.write_all(logstr.as_bytes()) {
+            warn!("failed to write log line: {:?}", e);
+        }
+
+        self.recent_watts.insert(0, data.watt);
+        avg_xsec
+    }
 }
 
 struct App {
     data: Vec<(String, u64, u64)>,
+    phase_current: [u64; 3],
     meter_value: Receiver<MeterData>,
-    log: BufWriter<File>,
+    cmd: Sender<SerialPortCmd>,
+    reconnect_path: String,
+    connected: bool,
+    meter_log: MeterLog,
 }
 
 fn demp_thread(tx: Sender<MeterData>) {
@@ -46,121 +130,314 @@ fn demp_thread(tx: Sender<MeterData>) {
             let data = MeterData {
                 time: Local::now(),
                 watt,
+                power_returned: rng.gen_range(0..500),
                 total: [total, 0],
+                tariff_indicator: if rng.gen_bool(0.5) { 1 } else { 2 },
+                gas: Some(rng.gen_range(0.0..2000.0)),
+                voltage: [Some(230.0), Some(230.0), Some(230.0)],
+                current: [Some(rng.gen_range(0..16)), Some(rng.gen_range(0..16)), Some(rng.gen_range(0..16))],
             };
 
         match tx.send(data) {
             Ok(_) => (),
             Err(e) => {
-                println!("Serial error {:?}", e);
-                break;            
+                warn!("demo channel closed: {:?}", e);
+                break;
             },
         }
     }
 }
 
-fn serial_thread(port: TTYPort, tx: Sender<MeterData>) {
-    let reader = dsmr5::Reader::new(port.bytes().map(|b| b.unwrap()));
+/// Parses one telegram into `MeterData`, if it's well-formed, and, if capturing, records it for
+/// Wireshark. Malformed or incomplete readouts are logged and skipped rather than panicking the
+/// reader thread.
+fn handle_readout(readout: dsmr5::Readout, capture: &mut Option<extcap::PcapWriter>) -> Option<MeterData> {
+    let telegram = match readout.to_telegram() {
+        Ok(telegram) => telegram,
+        Err(e) => {
+            warn!("malformed telegram, skipping: {:?}", e);
+            return None;
+        }
+    };
 
-    for readout in reader {
-        let telegram = readout.to_telegram().unwrap();
-        let state = dsmr5::Result::<dsmr5::state::State>::from(&telegram).unwrap();
-    
-        let mt = state.datetime.unwrap();
-    
-        let t = chrono::Local.ymd(2000 + mt.year as i32, mt.month as u32, mt.day as u32)
-                .and_hms(mt.hour as u32, mt.minute as u32, mt.second as u32);
+    let state = match dsmr5::Result::<dsmr5::state::State>::from(&telegram) {
+        Ok(state) => state,
+        Err(e) => {
+            warn!("could not decode telegram state, skipping: {:?}", e);
+            return None;
+        }
+    };
 
-        let data = MeterData {
-            time: t,
-            watt: (state.power_delivered.unwrap() * 1000.0) as u64,
-            total: [ (state.meterreadings[0].to.unwrap()) as u64, (state.meterreadings[1].to.unwrap()) as u64],
-            //total: 0, 
+    let Some(mt) = state.datetime else {
+        warn!("telegram has no datetime, skipping");
+        return None;
+    };
+    let Some(power_delivered) = state.power_delivered else {
+        warn!("telegram has no power_delivered, skipping");
+        return None;
+    };
+    let Some(normaal) = state.meterreadings[0].to else {
+        warn!("telegram has no normaal-tariff reading, skipping");
+        return None;
+    };
+    let Some(dal) = state.meterreadings[1].to else {
+        warn!("telegram has no dal-tariff reading, skipping");
+        return None;
+    };
+    let Some(power_received) = state.power_received else {
+        warn!("telegram has no power_received, skipping");
+        return None;
+    };
+    let Some(tariff_indicator) = state.tariff_indicator else {
+        warn!("telegram has no tariff_indicator, skipping");
+        return None;
+    };
+
+    // meter_reading is `(timestamp, value)`; we only need the reading itself.
+    let gas = state.slaves[0].meter_reading.as_ref().map(|(_, reading)| *reading);
+
+    let t = chrono::Local.ymd(2000 + mt.year as i32, mt.month as u32, mt.day as u32)
+            .and_hms(mt.hour as u32, mt.minute as u32, mt.second as u32);
+
+    if let Some(pcap) = capture.as_mut() {
+        // `buffer` is a fixed 2048-byte array zero-padded past the real telegram; trim the
+        // padding so Wireshark doesn't see a trailing run of NUL bytes on every packet.
+        let len = readout.buffer.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+
+        if let Err(e) = pcap.write_packet(t, &readout.buffer[..len]) {
+            warn!("extcap write error: {:?}", e);
+        }
+    }
+
+    Some(MeterData {
+        time: t,
+        watt: (power_delivered * 1000.0) as u64,
+        power_returned: (power_received * 1000.0) as u64,
+        total: [normaal as u64, dal as u64],
+        // [0] is reserved, [1] is the actual tariff indicator value.
+        tariff_indicator: tariff_indicator[1],
+        gas,
+        voltage: [state.lines[0].voltage, state.lines[1].voltage, state.lines[2].voltage],
+        current: [state.lines[0].current, state.lines[1].current, state.lines[2].current],
+    })
+}
+
+fn telegram_iter(port: TTYPort) -> dsmr5::Reader<impl Iterator<Item = io::Result<u8>>, io::Error> {
+    dsmr5::Reader::new(port.bytes())
+}
+
+/// Reads telegrams from an already-open `port` until the connection drops, sending each one
+/// over `tx`. Returns normally (rather than panicking) once the port stops yielding bytes, so
+/// the caller can decide whether to reopen it.
+fn read_telegrams(port: TTYPort, tx: &Sender<MeterData>, mut capture: Option<extcap::PcapWriter>) {
+    for result in telegram_iter(port) {
+        let readout = match result {
+            Ok(readout) => readout,
+            Err(e) => {
+                warn!("serial read error, skipping readout: {:?}", e);
+                continue;
+            }
+        };
+        let Some(data) = handle_readout(readout, &mut capture) else {
+            continue;
         };
+        if tx.send(data).is_err() {
+            break;
+        }
+    }
+}
 
-        match tx.send(data) {
-            Ok(_) => (),
+/// How long to wait before retrying a failed `serial::open`/`configure`.
+const RETRY_BACKOFF: Duration = Duration::from_secs(2);
+
+fn open_port(path: &str, baud: serial::BaudRate) -> serial::Result<TTYPort> {
+    let mut port = serial::open(path)?;
+    port.configure(&port_settings(baud))?;
+    port.set_timeout(Duration::from_secs(3))?;
+    Ok(port)
+}
+
+/// Supervises the serial connection: opens `path`, reads telegrams until the connection drops
+/// or the UI asks to reconnect elsewhere, then reopens it, waiting `RETRY_BACKOFF` after each
+/// failed open before the next attempt. Never exits on its
+/// own, so a momentary unplug or meter reset no longer kills the logger.
+fn serial_thread(mut path: String, baud: serial::BaudRate, tx: Sender<MeterData>, cmd_rx: Receiver<SerialPortCmd>) {
+    'reconnect: loop {
+        let port = match open_port(&path, baud) {
+            Ok(port) => port,
             Err(e) => {
-                println!("Serial error {:?}", e);
-                break;            
-            },
+                error!("error opening {}: {:?}", path, e);
+                match cmd_rx.recv_timeout(RETRY_BACKOFF) {
+                    Ok(SerialPortCmd::Connect(new_path)) => path = new_path,
+                    Ok(SerialPortCmd::Disconnect) => loop {
+                        match cmd_rx.recv() {
+                            Ok(SerialPortCmd::Connect(new_path)) => {
+                                path = new_path;
+                                break;
+                            },
+                            Ok(SerialPortCmd::Disconnect) => continue,
+                            Err(_) => return,
+                        }
+                    },
+                    Err(_) => (),
+                }
+                continue 'reconnect;
+            }
+        };
+
+        let mut telegrams = telegram_iter(port);
+
+        loop {
+            select! {
+                recv(cmd_rx) -> cmd => match cmd {
+                    Ok(SerialPortCmd::Connect(new_path)) => {
+                        path = new_path;
+                        continue 'reconnect;
+                    },
+                    Ok(SerialPortCmd::Disconnect) => {
+                        // Close the port and wait here, without retrying `path` on our own,
+                        // until the UI asks us to reconnect somewhere.
+                        drop(telegrams);
+                        loop {
+                            match cmd_rx.recv() {
+                                Ok(SerialPortCmd::Connect(new_path)) => {
+                                    path = new_path;
+                                    break;
+                                },
+                                Ok(SerialPortCmd::Disconnect) => continue,
+                                Err(_) => return,
+                            }
+                        }
+                        continue 'reconnect;
+                    },
+                    Err(_) => return,
+                },
+                default => match telegrams.next() {
+                    Some(Ok(readout)) => {
+                        if let Some(data) = handle_readout(readout, &mut None) {
+                            if tx.send(data).is_err() {
+                                return;
+                            }
+                        }
+                    },
+                    Some(Err(e)) => warn!("serial read error, skipping readout: {:?}", e),
+                    None => continue 'reconnect,
+                },
+            }
         }
     }
 }
 
-const AVG_SAMPLES: usize = 20;
+pub(crate) const AVG_SAMPLES: usize = 20;
 
 impl App {
-    fn new(rx: Receiver<MeterData>, log: BufWriter<File>) -> App {
+    fn new(rx: Receiver<MeterData>, cmd: Sender<SerialPortCmd>, reconnect_path: String, log: BufWriter<File>) -> App {
         App {
             data: Vec::with_capacity(AVG_SAMPLES),
+            phase_current: [0; 3],
             meter_value: rx,
-            log,
+            cmd,
+            reconnect_path,
+            connected: true,
+            meter_log: MeterLog::new(log),
         }
     }
 
     fn on_tick(&mut self, data: MeterData) {
+        self.connected = true;
+
+        let avg_xsec = self.meter_log.record(&data);
+        self.phase_current = [
+            data.current[0].unwrap_or(0),
+            data.current[1].unwrap_or(0),
+            data.current[2].unwrap_or(0),
+        ];
+
         // Handle label
         if self.data.len() == AVG_SAMPLES {
             self.data.pop().unwrap();
         }
 
-        let avg_len = std::cmp::min(self.data.len(), AVG_SAMPLES);
+        let t = data.time.format("%H%M%S").to_string();
+        self.data.insert(0, (t, data.watt, avg_xsec))
+    }
+}
 
-        let avg_xsec = {
-            let mut avg = data.watt;
+fn main() -> Result<(), Box<dyn Error>> {
+    let argv: Vec<String> = std::env::args().collect();
 
-            for (_s, data, _avg) in &self.data[0..avg_len] {
-                avg += data;
-            }
-            avg / (avg_len + 1) as u64           
-        };
+    if argv.iter().any(|a| a == "--extcap-interfaces") {
+        extcap::print_interfaces();
+        return Ok(());
+    }
+    if argv.iter().any(|a| a == "--extcap-config") {
+        extcap::print_config();
+        return Ok(());
+    }
+    if argv.iter().any(|a| a == "--extcap-dlts") {
+        extcap::print_dlts();
+        return Ok(());
+    }
+    if argv.iter().any(|a| a == "--capture") {
+        let fifo = arg_value(&argv, "--fifo").ok_or("--capture requires --fifo <path>")?;
+        let iface = arg_value(&argv, "--extcap-interface").ok_or("--capture requires --extcap-interface <name>")?;
+        if iface != extcap::INTERFACE {
+            return Err(format!("unknown extcap interface {iface}").into());
+        }
+        let port_path = arg_value(&argv, "--port").ok_or("--capture requires --port <path>")?;
+        let baud = arg_value(&argv, "--baud").and_then(|b| b.parse().ok()).unwrap_or(115_200);
+        let diag_log = arg_value(&argv, "--diag-log").unwrap_or_else(|| "dsm_logger.log".to_string());
 
-        let mut logstr = String::with_capacity(255);
+        logging::init(&diag_log)?;
 
-        logstr.push_str(data.time.format("%Y-%m-%d %H:%M:%S").to_string().as_str());
-        logstr.push_str(format!(";{};{};{};{};\n", data.total[0], data.total[1], data.watt, avg_xsec).as_str());
-        self.log.write_all(logstr.as_bytes()).unwrap();
+        let mut port = serial::open(&port_path)?;
+        port.configure(&port_settings(baud_rate(baud)))?;
+        port.set_timeout(std::time::Duration::from_secs(3))?;
 
-        let t = data.time.format("%H%M%S").to_string();
-        self.data.insert(0, (t, data.watt, avg_xsec))
-    }
-}
+        let pcap = extcap::PcapWriter::open(&fifo)?;
 
-const SETTINGS: serial::PortSettings = serial::PortSettings {
-    baud_rate:    serial::Baud115200,
-    char_size:    serial::Bits8,
-    parity:       serial::ParityNone,
-    stop_bits:    serial::Stop1,
-    flow_control: serial::FlowNone,
-};
+        let (tx, rx) = unbounded::<MeterData>();
+        std::thread::spawn(move || for _ in rx {});
 
-fn main() -> Result<(), Box<dyn Error>> {
+        read_telegrams(port, &tx, Some(pcap));
+        return Ok(());
+    }
 
-    let mut args = std::env::args();
+    let cli = Cli::parse();
 
-    let (tx, rx) = channel::<MeterData>();
+    logging::init(&cli.diag_log)?;
 
-    let f = OpenOptions::new().write(true).create(true).append(true).open("log.csv")?;
+    let (tx, rx) = unbounded::<MeterData>();
+    let (cmd_tx, cmd_rx) = unbounded::<SerialPortCmd>();
+
+    let f = OpenOptions::new().write(true).create(true).append(true).open(&cli.log_file)?;
     let mut logfile = BufWriter::new(f);
 
-    let header = "TIME;NORMAAL [kW];DAL [kW];POWER [W];AVG [W];\n";
+    let header = "TIME;NORMAAL [kW];DAL [kW];POWER [W];AVG [W];POWER RETURNED [W];TARIFF;GAS [m3];VOLTAGE L1 [V];VOLTAGE L2 [V];VOLTAGE L3 [V];CURRENT L1 [A];CURRENT L2 [A];CURRENT L3 [A];\n";
     logfile.write_all(header.as_bytes())?;
 
-    let _id = if let Some(path) = args.nth(1) {
-        let mut port = serial::open(&path)?;
-        port.configure(&SETTINGS)?;
-        // if let Err(e) = port.configure(&SETTINGS) {
-        //     println!("Can't setup port: {:?}", e);
-        //     return Ok(());
-        // }
-        port.set_timeout(std::time::Duration::from_secs(3))?;
-
-        std::thread::spawn( || serial_thread(port, tx))
+    let reconnect_path = if cli.demo {
+        String::new()
     } else {
+        match &cli.port {
+            Some(path) => path.clone(),
+            None => discover_port()?,
+        }
+    };
+
+    let _id = if cli.demo {
         std::thread::spawn( || demp_thread(tx))
+    } else {
+        let path = reconnect_path.clone();
+        let baud = baud_rate(cli.baud);
+
+        std::thread::spawn(move || serial_thread(path, baud, tx, cmd_rx))
     };
 
+    if cli.plot {
+        return plot::run(rx, MeterLog::new(logfile));
+    }
+
     // setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -169,7 +446,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     let mut terminal = Terminal::new(backend)?;
 
     // create app and run it
-    let app = App::new(rx, logfile);
+    let app = App::new(rx, cmd_tx, reconnect_path, logfile);
     let res = run_app(&mut terminal, app);
 
     // restore terminal
@@ -188,6 +465,10 @@ fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+fn arg_value(args: &[String], flag: &str) -> Option<String> {
+    args.iter().position(|a| a == flag).and_then(|i| args.get(i + 1)).cloned()
+}
+
 fn run_app<B: Backend>(
     terminal: &mut Terminal<B>,
     mut app: App,
@@ -197,16 +478,22 @@ fn run_app<B: Backend>(
 
         if crossterm::event::poll(Duration::from_millis(100))? {
             if let Event::Key(key) = event::read()? {
-                if let KeyCode::Char('q') = key.code {
-                    return Ok(());
+                match key.code {
+                    KeyCode::Char('q') => return Ok(()),
+                    KeyCode::Char('r') => {
+                        let _ = app.cmd.send(SerialPortCmd::Connect(app.reconnect_path.clone()));
+                    },
+                    KeyCode::Char('d') => {
+                        let _ = app.cmd.send(SerialPortCmd::Disconnect);
+                        app.connected = false;
+                    },
+                    _ => (),
                 }
             }
         }
         match app.meter_value.recv_timeout(Duration::from_millis(3000)) {
             Ok(data) => app.on_tick(data),
-            Err(e) => {
-                return Err(io::Error::new(io::ErrorKind::Other, format!("RX channel: {:?}: Quit!", e)));
-            },
+            Err(_) => app.connected = false,
         }
     }
 }
@@ -215,13 +502,19 @@ fn ui<B: Backend>(f: &mut Frame<B>, app: &App) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(2)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .constraints([Constraint::Percentage(34), Constraint::Percentage(33), Constraint::Percentage(33)].as_ref())
         .split(f.size());
 
-    let data_cur = app.data.iter().map(|f| (f.0.as_str(), f.1)).collect::<Vec<(&str, u64)>>();   
-    
+    let data_cur = app.data.iter().map(|f| (f.0.as_str(), f.1)).collect::<Vec<(&str, u64)>>();
+
+    let title = if app.connected {
+        "Current Watt".to_string()
+    } else {
+        "Current Watt (reconnecting... press 'r', or 'd' to stop)".to_string()
+    };
+
     let barchart = BarChart::default()
-        .block(Block::default().title("Current Watt").borders(Borders::ALL))
+        .block(Block::default().title(title).borders(Borders::ALL))
         .data(&data_cur)
         .bar_width(7)
         .bar_style(Style::default().fg(Color::Yellow))
@@ -237,4 +530,18 @@ fn ui<B: Backend>(f: &mut Frame<B>, app: &App) {
         .bar_style(Style::default().fg(Color::Green))
         .value_style(Style::default().fg(Color::Black).bg(Color::Yellow));
     f.render_widget(barchart, chunks[1]);
+
+    let phase_data = [
+        ("L1", app.phase_current[0]),
+        ("L2", app.phase_current[1]),
+        ("L3", app.phase_current[2]),
+    ];
+
+    let barchart = BarChart::default()
+        .block(Block::default().title("Current per phase [A]").borders(Borders::ALL))
+        .data(&phase_data)
+        .bar_width(7)
+        .bar_style(Style::default().fg(Color::Cyan))
+        .value_style(Style::default().fg(Color::Black).bg(Color::Cyan));
+    f.render_widget(barchart, chunks[2]);
 }
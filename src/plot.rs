@@ -0,0 +1,98 @@
+//! Scrolling egui_plot time-series view, selected with `--plot` as an alternative to the
+//! terminal bar charts. The meter/demo producer thread and its channel are unchanged; only this
+//! view layer differs from the TUI path in `main`.
+
+use std::error::Error;
+use std::time::Duration;
+
+use chrono::{Local, TimeZone};
+use crossbeam_channel::Receiver;
+use eframe::egui;
+use egui_plot::{Legend, Line, Plot, PlotPoints};
+
+use crate::{MeterData, MeterLog, AVG_SAMPLES};
+
+/// Renders an X-axis grid mark (seconds since the epoch) back to a local `HH:MM:SS` label, so
+/// the plot reads as a real timeline rather than bare Unix timestamps.
+fn x_axis_label(mark: egui_plot::GridMark, _max_chars: usize, _range: &std::ops::RangeInclusive<f64>) -> String {
+    Local
+        .timestamp_opt(mark.value as i64, 0)
+        .single()
+        .map(|t| t.format("%H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+/// How many samples each line keeps before scrolling off, so long sessions stay readable.
+const WINDOW_SAMPLES: usize = 600;
+
+struct PlotApp {
+    meter_value: Receiver<MeterData>,
+    meter_log: MeterLog,
+    watt: Vec<[f64; 2]>,
+    avg: Vec<[f64; 2]>,
+    total: [Vec<[f64; 2]>; 2],
+}
+
+impl PlotApp {
+    fn new(meter_value: Receiver<MeterData>, meter_log: MeterLog) -> PlotApp {
+        PlotApp {
+            meter_value,
+            meter_log,
+            watt: Vec::new(),
+            avg: Vec::new(),
+            total: [Vec::new(), Vec::new()],
+        }
+    }
+
+    fn push(&mut self, data: MeterData) {
+        let avg_xsec = self.meter_log.record(&data);
+        let x = data.time.timestamp() as f64;
+
+        let [total0, total1] = &mut self.total;
+        for series in [&mut self.watt, &mut self.avg, total0, total1] {
+            if series.len() == WINDOW_SAMPLES {
+                series.remove(0);
+            }
+        }
+
+        self.watt.push([x, data.watt as f64]);
+        self.avg.push([x, avg_xsec as f64]);
+        self.total[0].push([x, data.total[0] as f64]);
+        self.total[1].push([x, data.total[1] as f64]);
+    }
+}
+
+impl eframe::App for PlotApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        while let Ok(data) = self.meter_value.try_recv() {
+            self.push(data);
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            Plot::new("dsmr5")
+                .legend(Legend::default())
+                .x_axis_formatter(x_axis_label)
+                .show(ui, |plot_ui| {
+                    plot_ui.line(Line::new(PlotPoints::from(self.watt.clone())).name("Watt"));
+                    plot_ui.line(Line::new(PlotPoints::from(self.avg.clone())).name(format!("AVG x{AVG_SAMPLES}")));
+                    plot_ui.line(Line::new(PlotPoints::from(self.total[0].clone())).name("Normaal"));
+                    plot_ui.line(Line::new(PlotPoints::from(self.total[1].clone())).name("Dal"));
+                });
+        });
+
+        // Keep polling for new samples even without user input.
+        ctx.request_repaint_after(Duration::from_millis(200));
+    }
+}
+
+pub fn run(meter_value: Receiver<MeterData>, meter_log: MeterLog) -> Result<(), Box<dyn Error>> {
+    let options = eframe::NativeOptions::default();
+
+    eframe::run_native(
+        "dsm_logger",
+        options,
+        Box::new(|_cc| Box::new(PlotApp::new(meter_value, meter_log))),
+    )?;
+
+    Ok(())
+}